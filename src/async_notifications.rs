@@ -0,0 +1,42 @@
+// Async adapter on top of ZWaveManager::subscribe, for hubs that run their event loop and the
+// Z-Wave watcher on the same tokio runtime instead of polling a std::sync::mpsc::Receiver.
+// Gated behind the `async-notifications` feature so crates that don't use tokio don't pay for it.
+
+use ZWaveManager;
+use ZWaveNotification;
+use futures::{ Poll, Stream };
+use futures::sync::mpsc::{ unbounded, UnboundedReceiver };
+use std::thread;
+
+pub struct NotificationStream {
+    inner: UnboundedReceiver<ZWaveNotification>
+}
+
+impl Stream for NotificationStream {
+    type Item = ZWaveNotification;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<ZWaveNotification>, ()> {
+        self.inner.poll()
+    }
+}
+
+impl ZWaveManager {
+    // Same notifications as subscribe(), wrapped as a futures Stream. A background thread pumps
+    // the underlying std::sync::mpsc::Receiver into the async channel so the caller never blocks
+    // its executor on a synchronous recv().
+    pub fn subscribe_stream(&self) -> NotificationStream {
+        let receiver = self.subscribe();
+        let (tx, rx) = unbounded();
+
+        thread::spawn(move || {
+            while let Ok(notification) = receiver.recv() {
+                if tx.unbounded_send(notification).is_err() {
+                    break;
+                }
+            }
+        });
+
+        NotificationStream { inner: rx }
+    }
+}
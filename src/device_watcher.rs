@@ -0,0 +1,73 @@
+// Watches the configured device paths for disappearance/reappearance and re-adds the driver once
+// a stick that dropped off USB (a documented, frequent failure mode) comes back, instead of
+// requiring a process restart.
+
+use ZWaveManager;
+use ZWaveNotification;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::thread;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(1000);
+
+pub struct DeviceWatcher {
+    stop: Arc<AtomicBool>,
+}
+
+impl DeviceWatcher {
+    // Spawns a background thread that polls `devices` for openability and calls
+    // remove_driver/add_driver on `manager` as they disappear and reappear, debounced so a change
+    // only takes effect once two consecutive polls agree on it and a single flaky read doesn't
+    // flap the driver.
+    pub fn start(manager: Arc<ZWaveManager>, devices: Vec<String>) -> DeviceWatcher {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_thread = stop.clone();
+
+        thread::spawn(move || {
+            let mut present: HashMap<String, bool> = devices.iter()
+                .map(|device| (device.clone(), true))
+                .collect();
+            // The raw result of the previous poll for each device.
+            let mut last_seen = present.clone();
+
+            while !stop_thread.load(Ordering::Relaxed) {
+                for device in &devices {
+                    let openable = fs::File::open(device).is_ok();
+                    let seen_before = last_seen.insert(device.clone(), openable).unwrap_or(true);
+                    if openable != seen_before {
+                        continue;
+                    }
+                    let was_present = *present.get(device).unwrap_or(&true);
+
+                    if openable && !was_present {
+                        if manager.add_driver(device).is_ok() {
+                            manager.notify(ZWaveNotification::DriverReady(device.clone()));
+                        }
+                    } else if !openable && was_present {
+                        let _ = manager.remove_driver(device);
+                        manager.notify(ZWaveNotification::DriverRemoved(device.clone()));
+                    }
+
+                    present.insert(device.clone(), openable);
+                }
+
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+
+        DeviceWatcher { stop: stop }
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,131 @@
+// Higher-level, typed view over the raw ValueID zoo. Consumers that just want to flip a light
+// shouldn't have to know which of the bool/byte/decimal/list/short/string value types a given
+// command class uses, or decode genre/instance/index themselves.
+
+use CommandClass;
+use Node;
+use Result;
+use State;
+use Value;
+use ValueID;
+use ZWaveManager;
+
+pub struct Switch {
+    node: Node,
+    value: ValueID,
+}
+
+impl Switch {
+    pub fn get_node(&self) -> Node {
+        self.node
+    }
+
+    pub fn turn_on(&self, manager: &ZWaveManager) -> Result<()> {
+        manager.set_value(&self.value, Value::Bool(true))
+    }
+
+    pub fn turn_off(&self, manager: &ZWaveManager) -> Result<()> {
+        manager.set_value(&self.value, Value::Bool(false))
+    }
+
+    pub fn is_on(&self, manager: &ZWaveManager) -> Result<bool> {
+        match try!(manager.get_value(&self.value)) {
+            Value::Bool(on) => Ok(on),
+            _ => Ok(false)
+        }
+    }
+}
+
+pub struct Dimmer {
+    node: Node,
+    value: ValueID,
+}
+
+impl Dimmer {
+    pub fn get_node(&self) -> Node {
+        self.node
+    }
+
+    pub fn set_level(&self, manager: &ZWaveManager, level: u8) -> Result<()> {
+        manager.set_value(&self.value, Value::Byte(level))
+    }
+
+    pub fn get_level(&self, manager: &ZWaveManager) -> Result<u8> {
+        match try!(manager.get_value(&self.value)) {
+            Value::Byte(level) => Ok(level),
+            _ => Ok(0)
+        }
+    }
+}
+
+pub struct SensorMultilevel {
+    node: Node,
+    value: ValueID,
+}
+
+impl SensorMultilevel {
+    pub fn get_node(&self) -> Node {
+        self.node
+    }
+
+    pub fn get_reading(&self, manager: &ZWaveManager) -> Result<Value> {
+        manager.get_value(&self.value)
+    }
+}
+
+pub enum Device {
+    Switch(Switch),
+    Dimmer(Dimmer),
+    SensorMultilevel(SensorMultilevel),
+}
+
+impl Device {
+    pub fn get_node(&self) -> Node {
+        match *self {
+            Device::Switch(ref device) => device.get_node(),
+            Device::Dimmer(ref device) => device.get_node(),
+            Device::SensorMultilevel(ref device) => device.get_node(),
+        }
+    }
+}
+
+// Groups the state's value ids by node and classifies each one by its command class into a typed
+// device. A single node with several recognised command classes yields several Devices.
+pub fn devices_from_state(state: &State) -> Vec<Device> {
+    let mut devices = Vec::new();
+
+    for node in state.get_nodes() {
+        let node_values = state.get_values().iter()
+            .filter(|value| value.get_home_id() == node.get_home_id() && value.get_node_id() == node.get_node_id());
+
+        for value in node_values {
+            let device = match value.get_command_class() {
+                CommandClass::COMMAND_CLASS_SWITCH_BINARY => Some(Device::Switch(Switch {
+                    node: *node,
+                    value: value.clone()
+                })),
+                CommandClass::COMMAND_CLASS_SWITCH_MULTILEVEL => Some(Device::Dimmer(Dimmer {
+                    node: *node,
+                    value: value.clone()
+                })),
+                CommandClass::COMMAND_CLASS_SENSOR_MULTILEVEL => Some(Device::SensorMultilevel(SensorMultilevel {
+                    node: *node,
+                    value: value.clone()
+                })),
+                _ => None
+            };
+
+            if let Some(device) = device {
+                devices.push(device);
+            }
+        }
+    }
+
+    devices
+}
+
+impl ZWaveManager {
+    pub fn get_devices(&self) -> Vec<Device> {
+        devices_from_state(&self.get_state())
+    }
+}
@@ -0,0 +1,41 @@
+// Plug-and-play controller discovery: enumerate serial/USB devices via libudev and filter down to
+// the ones whose VID:PID matches a known Z-Wave controller bridge chip, so callers don't have to
+// hard-code a /dev/tty* path. Gated behind the `udev` feature, like rusty-keys does.
+
+use Error;
+use KNOWN_USB_ZWAVE_CONTROLLERS;
+use Result;
+use udev;
+
+pub fn discover_devices() -> Result<Vec<String>> {
+    let mut enumerator = try!(udev::Enumerator::new());
+    try!(enumerator.match_subsystem("tty"));
+
+    let mut devices = Vec::new();
+    for device in try!(enumerator.scan_devices()) {
+        if !is_known_zwave_controller(&device) {
+            continue;
+        }
+
+        if let Some(devnode) = device.devnode() {
+            devices.push(devnode.to_string_lossy().into_owned());
+        }
+    }
+
+    Ok(devices)
+}
+
+fn is_known_zwave_controller(device: &udev::Device) -> bool {
+    let usb_device = match device.parent_with_subsystem("usb") {
+        Ok(Some(parent)) => parent,
+        _ => return false
+    };
+
+    let vid = usb_device.property_value("ID_VENDOR_ID").and_then(|v| v.to_str()).and_then(|v| u16::from_str_radix(v, 16).ok());
+    let pid = usb_device.property_value("ID_MODEL_ID").and_then(|v| v.to_str()).and_then(|v| u16::from_str_radix(v, 16).ok());
+
+    match (vid, pid) {
+        (Some(vid), Some(pid)) => KNOWN_USB_ZWAVE_CONTROLLERS.contains(&(vid, pid)),
+        _ => false
+    }
+}
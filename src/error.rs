@@ -1,6 +1,9 @@
 use openzwave;
 use std::io;
 use notify;
+use serde_json;
+#[cfg(feature = "udev")]
+use udev;
 
 #[derive(Debug)]
 pub enum Error {
@@ -8,10 +11,62 @@ pub enum Error {
     NoDeviceFound,
     CannotReadDevice(String, io::Error),
     FsNotifyError(notify::Error),
+    RpcIoError(io::Error),
+    RpcJsonError(serde_json::Error),
+    RpcUnknownValue(u64),
+    NetworkKeyIoError(String, io::Error),
+    ControllerDisconnected(String),
+    #[cfg(feature = "udev")]
+    UdevError(udev::Error),
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
 
+// Modelled after std::io::Error's ErrorKind: a coarse classification callers can match on instead
+// of matching every Error variant, and the thing the retry-with-backoff helper in lib.rs uses to
+// decide whether "the stick is momentarily busy" is worth retrying versus "the stick is gone".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    DeviceUnreadable,
+    DeviceBusy,
+    NotFound,
+    Interrupted,
+    Driver,
+    Watch,
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            Error::CannotReadDevice(_, ref cause) => match cause.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => ErrorKind::DeviceBusy,
+                io::ErrorKind::Interrupted => ErrorKind::Interrupted,
+                io::ErrorKind::NotFound => ErrorKind::NotFound,
+                _ => ErrorKind::DeviceUnreadable
+            },
+            Error::NoDeviceFound => ErrorKind::NotFound,
+            Error::ControllerDisconnected(_) => ErrorKind::NotFound,
+            Error::FsNotifyError(_) => ErrorKind::Watch,
+            Error::OpenzwaveError(_) => ErrorKind::Driver,
+            Error::NetworkKeyIoError(_, _) => ErrorKind::DeviceUnreadable,
+            Error::RpcIoError(_) => ErrorKind::Driver,
+            Error::RpcJsonError(_) => ErrorKind::Driver,
+            Error::RpcUnknownValue(_) => ErrorKind::Driver,
+            #[cfg(feature = "udev")]
+            Error::UdevError(_) => ErrorKind::Driver,
+        }
+    }
+
+    // Whether retrying the operation that produced this error after a short wait has a reasonable
+    // chance of succeeding, as opposed to the device being gone outright.
+    pub fn is_transient(&self) -> bool {
+        match self.kind() {
+            ErrorKind::DeviceBusy | ErrorKind::Interrupted => true,
+            _ => false
+        }
+    }
+}
+
 use std::fmt;
 use std::error;
 
@@ -21,6 +76,13 @@ impl fmt::Display for Error {
             Error::OpenzwaveError(ref cause) => write!(formatter, "{}", cause),
             Error::CannotReadDevice(ref message, ref cause) => write!(formatter, "The device {} is not readable: {}", message, cause),
             Error::FsNotifyError(ref cause) => write!(formatter, "Could not watch the device file: {}", cause),
+            Error::RpcIoError(ref cause) => write!(formatter, "RPC transport error: {}", cause),
+            Error::RpcJsonError(ref cause) => write!(formatter, "RPC message was not valid JSON: {}", cause),
+            Error::RpcUnknownValue(id) => write!(formatter, "No value with id {} is known", id),
+            Error::NetworkKeyIoError(ref path, ref cause) => write!(formatter, "Could not access the persisted NetworkKey at {}: {}", path, cause),
+            Error::ControllerDisconnected(ref device) => write!(formatter, "The controller at {} was disconnected", device),
+            #[cfg(feature = "udev")]
+            Error::UdevError(ref cause) => write!(formatter, "udev discovery failed: {}", cause),
             _ => write!(formatter, "{}", error::Error::description(self))
         }
     }
@@ -32,7 +94,14 @@ impl error::Error for Error {
             Error::OpenzwaveError(ref cause) => cause.description(),
             Error::CannotReadDevice(_, _) => "Couldn't read the device",
             Error::FsNotifyError(_) => "Could not watch the device file",
-            Error::NoDeviceFound => "No suitable device was found"
+            Error::NoDeviceFound => "No suitable device was found",
+            Error::RpcIoError(_) => "RPC transport error",
+            Error::RpcJsonError(_) => "RPC message was not valid JSON",
+            Error::RpcUnknownValue(_) => "No value with that id is known",
+            Error::NetworkKeyIoError(_, _) => "Could not access the persisted NetworkKey",
+            Error::ControllerDisconnected(_) => "The controller was disconnected",
+            #[cfg(feature = "udev")]
+            Error::UdevError(_) => "udev discovery failed"
         }
     }
 
@@ -41,6 +110,11 @@ impl error::Error for Error {
             Error::OpenzwaveError(ref cause) => Some(cause),
             Error::CannotReadDevice(_, ref cause) => Some(cause),
             Error::FsNotifyError(ref cause) => Some(cause),
+            Error::RpcIoError(ref cause) => Some(cause),
+            Error::RpcJsonError(ref cause) => Some(cause),
+            Error::NetworkKeyIoError(_, ref cause) => Some(cause),
+            #[cfg(feature = "udev")]
+            Error::UdevError(ref cause) => Some(cause),
             _ => None
         }
     }
@@ -57,3 +131,10 @@ impl From<notify::Error> for Error {
         Error::FsNotifyError(error)
     }
 }
+
+#[cfg(feature = "udev")]
+impl From<udev::Error> for Error {
+    fn from(error: udev::Error) -> Error {
+        Error::UdevError(error)
+    }
+}
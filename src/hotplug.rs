@@ -0,0 +1,89 @@
+// Watches a single controller device path with `notify` (inotify on Linux) and tears down/re-inits
+// the OpenZWave driver as the path disappears and reappears, instead of leaving it dead until the
+// whole process restarts. Complements device_watcher's polling approach with an event-driven one.
+
+use Error;
+use Result;
+use ZWaveManager;
+use ZWaveNotification;
+use notify::{ self, DebouncedEvent, RecursiveMode, Watcher };
+use std::path::Path;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+pub struct HotplugWatcher {
+    stop: Arc<AtomicBool>,
+}
+
+impl HotplugWatcher {
+    // Starts watching `device` for removal/recreation. The watcher thread outlives this call;
+    // drop the returned HotplugWatcher (or call stop()) to tear it down.
+    pub fn start(manager: Arc<ZWaveManager>, device: String) -> Result<HotplugWatcher> {
+        let (tx, rx) = channel();
+        let mut fs_watcher = try!(notify::watcher(tx, Duration::from_secs(2)));
+
+        // Watching the device special file itself doesn't survive a real unplug/replug: removing
+        // it deletes the inode, which the kernel auto-removes the inotify watch for (IN_DELETE_SELF
+        // / IN_IGNORED), and a replugged controller gets a new inode at the same path that this
+        // watch would never see get created. Watch the parent directory instead and filter events
+        // down to this device's path.
+        let watch_dir = Path::new(&device).parent().unwrap_or_else(|| Path::new("."));
+        try!(fs_watcher.watch(watch_dir, RecursiveMode::NonRecursive));
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_thread = stop.clone();
+
+        thread::spawn(move || {
+            // Keep the notify::Watcher alive for as long as this thread runs the event loop.
+            let _fs_watcher = fs_watcher;
+
+            while !stop_thread.load(Ordering::Relaxed) {
+                match rx.recv_timeout(Duration::from_millis(500)) {
+                    Ok(DebouncedEvent::Remove(ref path)) if is_device_path(path, &device) => {
+                        let _ = manager.remove_driver(&device);
+                        manager.notify(ZWaveNotification::ControllerDisconnected(device.clone()));
+                    },
+
+                    Ok(DebouncedEvent::Create(ref path)) if is_device_path(path, &device) => {
+                        if manager.add_driver(&device).is_ok() {
+                            manager.notify(ZWaveNotification::ControllerReconnected(device.clone()));
+                        }
+                    },
+
+                    // A stale watch whose path no longer resolves isn't the same thing as the
+                    // controller having been unplugged, so it shouldn't masquerade as a disconnect.
+                    Ok(DebouncedEvent::Error(notify::Error::WatchNotFound, _)) |
+                    Ok(DebouncedEvent::Error(notify::Error::PathNotFound, _)) => continue,
+
+                    Ok(_) => continue,
+                    Err(_) => continue,
+                }
+            }
+        });
+
+        Ok(HotplugWatcher { stop: stop })
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for HotplugWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn is_device_path(event_path: &Path, device: &str) -> bool {
+    event_path == Path::new(device)
+}
+
+// The Error variant callers should use to report a lost controller outside of the event stream,
+// e.g. when a command fails because the driver has already been torn down.
+pub fn disconnected_error(device: &str) -> Error {
+    Error::ControllerDisconnected(device.to_owned())
+}
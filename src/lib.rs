@@ -2,7 +2,27 @@
 extern crate log;
 extern crate serial_ports;
 extern crate openzwave;
+extern crate notify;
+#[macro_use]
+extern crate serde_json;
+extern crate rand;
+#[cfg(feature = "async-notifications")]
+extern crate futures;
+#[cfg(feature = "udev")]
+extern crate udev;
 mod error;
+pub mod device_watcher;
+pub mod devices;
+pub mod hotplug;
+#[cfg(feature = "udev")]
+pub mod discovery;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+#[cfg(feature = "async-notifications")]
+mod async_notifications;
+
+#[cfg(feature = "async-notifications")]
+pub use async_notifications::NotificationStream;
 
 pub use error::{ Error, Result };
 use serial_ports::{ ListPortInfo, ListPorts };
@@ -22,15 +42,19 @@ fn get_default_devices() -> Vec<String> {
     vec!["\\\\.\\COM6".to_owned()]
 }
 
+// Known VID:PID pairs for Z-Wave USB controllers, shared by the serial_ports-based enumeration
+// below and the udev-based discovery module.
+pub(crate) const KNOWN_USB_ZWAVE_CONTROLLERS: [(u16, u16); 3] = [
+    // VID     PID
+    //-----   -----
+    (0x0658, 0x0200),   // Aeotech Z-Stick Gen-5
+    (0x0658, 0x0280),   // UZB1
+    (0x10c4, 0xea60),   // Aeotech Z-Stick S2
+];
+
 #[cfg(unix)]
 fn is_usb_zwave_device(port: &ListPortInfo) -> bool {
-    let default_usb_devices = [
-        // VID     PID
-        //-----   -----
-        (0x0658, 0x0200),   // Aeotech Z-Stick Gen-5
-        (0x0658, 0x0280),   // UZB1
-        (0x10c4, 0xea60),   // Aeotech Z-Stick S2
-    ];
+    let default_usb_devices = KNOWN_USB_ZWAVE_CONTROLLERS;
 
     // Is it one of the vid/pids in the table?
     if let UsbPort(ref info) = port.port_type {
@@ -42,6 +66,16 @@ fn is_usb_zwave_device(port: &ListPortInfo) -> bool {
 
 #[cfg(unix)]
 fn get_default_devices() -> Vec<String> {
+    // When built with udev support, prefer its VID:PID-filtered enumeration over the
+    // serial_ports-based one below, so a caller that didn't pass InitOptions.devices gets
+    // plug-and-play discovery instead of always falling through to the hardcoded path table.
+    #[cfg(feature = "udev")]
+    {
+        match discovery::discover_devices() {
+            Ok(devices) => if !devices.is_empty() { return devices; },
+            Err(ref err) => error!("[OpenzwaveStateful] udev discovery failed, falling back to the known VID:PID table: {}", err),
+        }
+    }
 
     // Enumerate all of the serial devices and see if any of them match our
     // known VID:PID.
@@ -105,12 +139,50 @@ impl fmt::Display for ControllerInfo {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct SceneInfo {
+    scene_id: u8,
+    label: String,
+    values: Vec<(ValueID, Value)>,
+}
+
+impl SceneInfo {
+    fn new(scene_id: u8, label: String, values: Vec<(ValueID, Value)>) -> SceneInfo {
+        SceneInfo { scene_id: scene_id, label: label, values: values }
+    }
+
+    pub fn get_id(&self) -> u8 {
+        self.scene_id
+    }
+
+    pub fn get_label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn get_values(&self) -> &Vec<(ValueID, Value)> {
+        &self.values
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeStatus {
+    Unknown,
+    Alive,
+    Awake,
+    Asleep,
+    Dead,
+}
+
 #[derive(Debug, Clone)]
 pub struct State {
     controllers: HashMap<Controller, ControllerInfo>,
     nodes: BTreeSet<Node>,
     nodes_map: HashMap<Controller, BTreeSet<Node>>,
     value_ids: BTreeSet<ValueID>,
+    scenes: HashMap<u8, SceneInfo>,
+    node_statuses: HashMap<Node, NodeStatus>,
+    essential_node_queries_complete: BTreeSet<Node>,
+    node_queries_complete: BTreeSet<Node>,
 }
 
 impl State {
@@ -119,7 +191,11 @@ impl State {
             controllers: HashMap::new(),
             nodes: BTreeSet::new(),
             nodes_map: HashMap::new(),
-            value_ids: BTreeSet::new()
+            value_ids: BTreeSet::new(),
+            scenes: HashMap::new(),
+            node_statuses: HashMap::new(),
+            essential_node_queries_complete: BTreeSet::new(),
+            node_queries_complete: BTreeSet::new()
         }
     }
 
@@ -143,6 +219,10 @@ impl State {
         &self.value_ids
     }
 
+    pub fn find_value_by_id(&self, id: u64) -> Option<ValueID> {
+        self.value_ids.iter().find(|value_id| value_id.get_id() == id).cloned()
+    }
+
     pub fn add_node(&mut self, node: Node) {
         let node_set = self.nodes_map.entry(node.get_controller()).or_insert(BTreeSet::new());
         node_set.insert(node);
@@ -154,6 +234,36 @@ impl State {
             node_set.remove(&node);
         }
         self.nodes.remove(&node);
+        self.node_statuses.remove(&node);
+        self.essential_node_queries_complete.remove(&node);
+        self.node_queries_complete.remove(&node);
+    }
+
+    pub fn get_node_status(&self, node: &Node) -> NodeStatus {
+        *self.node_statuses.get(node).unwrap_or(&NodeStatus::Unknown)
+    }
+
+    pub fn get_failed_nodes(&self) -> Vec<Node> {
+        self.node_statuses.iter()
+            .filter(|&(_, status)| *status == NodeStatus::Dead)
+            .map(|(node, _)| *node)
+            .collect()
+    }
+
+    pub fn is_node_ready(&self, node: &Node) -> bool {
+        self.node_queries_complete.contains(node)
+    }
+
+    fn set_node_status(&mut self, node: Node, status: NodeStatus) {
+        self.node_statuses.insert(node, status);
+    }
+
+    fn set_essential_node_queries_complete(&mut self, node: Node) {
+        self.essential_node_queries_complete.insert(node);
+    }
+
+    fn set_node_queries_complete(&mut self, node: Node) {
+        self.node_queries_complete.insert(node);
     }
 
     pub fn add_value_id(&mut self, value_id: ValueID) {
@@ -163,6 +273,94 @@ impl State {
     pub fn remove_value_id(&mut self, value_id: ValueID) {
         self.value_ids.remove(&value_id);
     }
+
+    pub fn get_scenes(&self) -> &HashMap<u8, SceneInfo> {
+        &self.scenes
+    }
+
+    fn set_scenes(&mut self, scenes: HashMap<u8, SceneInfo>) {
+        self.scenes = scenes;
+    }
+
+    // Snapshots the controllers, nodes (with their status), value ids and scenes as a JSON string,
+    // so a web/UI layer can render the current Z-Wave network without re-deriving it from the live
+    // Rust structs. The node query-completion flags are not included. This only covers what State
+    // itself tracks; ZWaveManager::to_json additionally resolves each value's
+    // label and current reading, which require a call into the underlying manager.
+    pub fn to_json(&self) -> String {
+        let controllers: Vec<_> = self.controllers.iter()
+            .map(|(controller, info)| controller_info_to_json(controller, info))
+            .collect();
+        let nodes: Vec<_> = self.nodes.iter()
+            .map(|node| node_to_json(node, self.get_node_status(node)))
+            .collect();
+        let value_ids: Vec<_> = self.value_ids.iter().map(value_id_to_json).collect();
+        let scenes: Vec<_> = self.scenes.values().map(scene_info_to_json).collect();
+
+        json!({
+            "controllers": controllers,
+            "nodes": nodes,
+            "value_ids": value_ids,
+            "scenes": scenes,
+        }).to_string()
+    }
+}
+
+fn controller_info_to_json(controller: &Controller, info: &ControllerInfo) -> serde_json::Value {
+    json!({
+        "home_id": controller.get_home_id(),
+        "last_state": info.last_state.to_string(),
+        "last_error": info.last_error.to_string(),
+    })
+}
+
+fn node_to_json(node: &Node, status: NodeStatus) -> serde_json::Value {
+    json!({
+        "home_id": node.get_home_id(),
+        "node_id": node.get_node_id(),
+        "status": format!("{:?}", status),
+    })
+}
+
+fn value_id_to_json(value_id: &ValueID) -> serde_json::Value {
+    json!({
+        "id": value_id.get_id(),
+        "genre": format!("{:?}", value_id.get_genre()),
+        "command_class": format!("{:?}", value_id.get_command_class()),
+        "type": format!("{:?}", value_id.get_type()),
+        "index": value_id.get_index(),
+        "instance": value_id.get_instance(),
+    })
+}
+
+fn scene_info_to_json(scene: &SceneInfo) -> serde_json::Value {
+    let values: Vec<_> = scene.get_values().iter().map(|&(ref value_id, ref value)| {
+        let mut entry = value_id_to_json(value_id);
+        entry["value"] = json!(format!("{:?}", value));
+        entry
+    }).collect();
+
+    json!({
+        "id": scene.get_id(),
+        "label": scene.get_label(),
+        "values": values,
+    })
+}
+
+// The various OpenZWave value types collapsed into one enum so callers can match on the kind
+// of data they got back instead of guessing which typed getter/setter to call on a ValueID.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Byte(u8),
+    Short(i16),
+    Int(i32),
+    Decimal(String),
+    List(String),
+    String(String),
+    Raw(Vec<u8>),
+    Button(bool),
+    Schedule,
 }
 
 pub struct ZWaveManager {
@@ -177,7 +375,7 @@ impl ZWaveManager {
         let manager = ZWaveManager {
             watcher: ZWaveWatcher {
                 state: Arc::new(Mutex::new(State::new())),
-                sender: Arc::new(Mutex::new(tx))
+                senders: Arc::new(Mutex::new(vec![tx]))
             },
             ozw_manager: manager
         };
@@ -185,6 +383,16 @@ impl ZWaveManager {
         (manager, rx)
     }
 
+    // Registers a new independent subscriber. Every ZWaveNotification emitted from now on is
+    // fanned out to this receiver as well as to every other live subscriber.
+    pub fn subscribe(&self) -> mpsc::Receiver<ZWaveNotification> {
+        self.watcher.subscribe()
+    }
+
+    pub fn listener_count(&self) -> usize {
+        self.watcher.listener_count()
+    }
+
     pub fn add_node(&self, home_id: u32, secure: bool) -> Result<()> {
         try!(self.ozw_manager.add_node(home_id, secure));
         Ok(())
@@ -211,12 +419,172 @@ impl ZWaveManager {
         self.ozw_manager.heal_network_node(home_id, node_id, do_rr);
     }
 
+    pub fn has_node_failed(&self, home_id: u32, node_id: u8) -> Result<bool> {
+        Ok(try!(self.ozw_manager.has_node_failed(home_id, node_id)))
+    }
+
+    pub fn remove_failed_node(&self, home_id: u32, node_id: u8) -> Result<()> {
+        try!(self.ozw_manager.remove_failed_node(home_id, node_id));
+        Ok(())
+    }
+
+    pub fn request_network_update(&self, home_id: u32) -> Result<()> {
+        try!(self.ozw_manager.request_network_update(home_id));
+        Ok(())
+    }
+
+    pub fn get_node_status(&self, node: &Node) -> NodeStatus {
+        self.get_state().get_node_status(node)
+    }
+
+    pub fn get_failed_nodes(&self) -> Vec<Node> {
+        self.get_state().get_failed_nodes()
+    }
+
+    pub fn is_node_ready(&self, node: &Node) -> bool {
+        self.get_state().is_node_ready(node)
+    }
+
+    pub fn get_value(&self, id: &ValueID) -> Result<Value> {
+        Ok(match id.get_type() {
+            ValueType::ValueType_Bool => Value::Bool(try!(self.ozw_manager.get_value_as_bool(id))),
+            ValueType::ValueType_Byte => Value::Byte(try!(self.ozw_manager.get_value_as_byte(id))),
+            ValueType::ValueType_Short => Value::Short(try!(self.ozw_manager.get_value_as_short(id))),
+            ValueType::ValueType_Int => Value::Int(try!(self.ozw_manager.get_value_as_int(id))),
+            ValueType::ValueType_Decimal => Value::Decimal(try!(self.ozw_manager.get_value_as_float(id)).to_string()),
+            ValueType::ValueType_List => Value::List(try!(self.ozw_manager.get_value_list_selection_string(id))),
+            ValueType::ValueType_String => Value::String(try!(self.ozw_manager.get_value_as_string(id))),
+            ValueType::ValueType_Raw => Value::Raw(try!(self.ozw_manager.get_value_as_raw(id))),
+            ValueType::ValueType_Button => Value::Button(try!(self.ozw_manager.is_value_pressed(id))),
+            ValueType::ValueType_Schedule => Value::Schedule,
+        })
+    }
+
+    pub fn set_value(&self, id: &ValueID, value: Value) -> Result<()> {
+        try!(match value {
+            Value::Bool(v) => self.ozw_manager.set_value_bool(id, v),
+            Value::Byte(v) => self.ozw_manager.set_value_byte(id, v),
+            Value::Short(v) => self.ozw_manager.set_value_short(id, v),
+            Value::Int(v) => self.ozw_manager.set_value_int(id, v),
+            Value::Decimal(v) => self.ozw_manager.set_value_string(id, &v),
+            Value::List(v) => self.ozw_manager.set_value_list_selection_string(id, &v),
+            Value::String(v) => self.ozw_manager.set_value_string(id, &v),
+            Value::Raw(v) => self.ozw_manager.set_value_raw(id, &v),
+            Value::Button(true) => self.ozw_manager.press_button(id),
+            Value::Button(false) => self.ozw_manager.release_button(id),
+            Value::Schedule => return Ok(()),
+        });
+        Ok(())
+    }
+
+    pub fn refresh_value(&self, id: &ValueID) -> Result<()> {
+        try!(self.ozw_manager.refresh_value(id));
+        Ok(())
+    }
+
+    pub fn create_scene(&self, label: &str) -> Result<u8> {
+        let scene_id = try!(self.ozw_manager.create_scene());
+        try!(self.ozw_manager.set_scene_label(scene_id, label));
+        try!(self.refresh_scenes());
+        Ok(scene_id)
+    }
+
+    pub fn remove_scene(&self, scene_id: u8) -> Result<()> {
+        try!(self.ozw_manager.remove_scene(scene_id));
+        self.refresh_scenes()
+    }
+
+    pub fn set_scene_label(&self, scene_id: u8, label: &str) -> Result<()> {
+        try!(self.ozw_manager.set_scene_label(scene_id, label));
+        self.refresh_scenes()
+    }
+
+    pub fn add_scene_value(&self, scene_id: u8, id: &ValueID, value: Value) -> Result<()> {
+        try!(match value {
+            Value::Bool(v) => self.ozw_manager.add_scene_value_bool(scene_id, id, v),
+            Value::Byte(v) => self.ozw_manager.add_scene_value_byte(scene_id, id, v),
+            Value::Short(v) => self.ozw_manager.add_scene_value_short(scene_id, id, v),
+            Value::Int(v) => self.ozw_manager.add_scene_value_int(scene_id, id, v),
+            Value::Decimal(ref v) => self.ozw_manager.add_scene_value_string(scene_id, id, v),
+            Value::List(ref v) => self.ozw_manager.add_scene_value_list_selection_string(scene_id, id, v),
+            Value::String(ref v) => self.ozw_manager.add_scene_value_string(scene_id, id, v),
+            Value::Raw(ref v) => self.ozw_manager.add_scene_value_raw(scene_id, id, v),
+            Value::Button(_) | Value::Schedule => return Ok(()),
+        });
+        self.refresh_scenes()
+    }
+
+    pub fn remove_scene_value(&self, scene_id: u8, id: &ValueID) -> Result<()> {
+        try!(self.ozw_manager.remove_scene_value(scene_id, id));
+        self.refresh_scenes()
+    }
+
+    pub fn activate_scene(&self, scene_id: u8) -> Result<()> {
+        try!(self.ozw_manager.activate_scene(scene_id));
+        Ok(())
+    }
+
+    pub fn get_scenes(&self) -> HashMap<u8, SceneInfo> {
+        self.get_state().get_scenes().clone()
+    }
+
+    // Same snapshot as State::to_json, but with each value's label and current reading filled in,
+    // since resolving those requires a call into the underlying manager that State doesn't have.
+    pub fn to_json(&self) -> String {
+        let state = self.get_state();
+
+        let controllers: Vec<_> = state.get_controllers().iter()
+            .map(|(controller, info)| controller_info_to_json(controller, info))
+            .collect();
+        let nodes: Vec<_> = state.get_nodes().iter()
+            .map(|node| node_to_json(node, state.get_node_status(node)))
+            .collect();
+        let value_ids: Vec<_> = state.get_values().iter().map(|value_id| {
+            let mut entry = value_id_to_json(value_id);
+            let label = self.ozw_manager.get_value_label(value_id).unwrap_or_default();
+            let value = self.get_value(value_id).map(|v| format!("{:?}", v)).unwrap_or_default();
+            entry["label"] = json!(label);
+            entry["value"] = json!(value);
+            entry
+        }).collect();
+        let scenes: Vec<_> = state.get_scenes().values().map(scene_info_to_json).collect();
+
+        json!({
+            "controllers": controllers,
+            "nodes": nodes,
+            "value_ids": value_ids,
+            "scenes": scenes,
+        }).to_string()
+    }
+
+    // Re-reads every scene from the underlying manager and replaces the cached table in one go.
+    // Called after every mutating scene call since OpenZWave doesn't notify us of scene changes,
+    // and once at startup (see init()) since OpenZWave persists scenes on the controller itself,
+    // so a fresh process would otherwise report an empty scene table until it made its own
+    // mutating scene call.
+    pub(crate) fn refresh_scenes(&self) -> Result<()> {
+        let mut scenes = HashMap::new();
+        for scene_id in try!(self.ozw_manager.get_all_scene_ids()) {
+            let label = try!(self.ozw_manager.get_scene_label(scene_id));
+            let mut values = Vec::new();
+            for value_id in try!(self.ozw_manager.scene_get_values(scene_id)) {
+                if let Ok(value) = self.get_value(&value_id) {
+                    values.push((value_id, value));
+                }
+            }
+            scenes.insert(scene_id, SceneInfo::new(scene_id, label, values));
+        }
+
+        self.watcher.get_state().set_scenes(scenes);
+        Ok(())
+    }
+
     fn add_watcher(&mut self) -> Result<()> {
         try!(self.ozw_manager.add_watcher(self.watcher.clone()));
         Ok(())
     }
 
-    fn add_driver(&mut self, device: &str) -> Result<()> {
+    pub(crate) fn add_driver(&self, device: &str) -> Result<()> {
         try!(match device {
             "usb" => self.ozw_manager.add_usb_driver(),
             _ => self.ozw_manager.add_driver(&device)
@@ -224,10 +592,24 @@ impl ZWaveManager {
         Ok(())
     }
 
+    pub(crate) fn remove_driver(&self, device: &str) -> Result<()> {
+        try!(match device {
+            "usb" => self.ozw_manager.remove_usb_driver(),
+            _ => self.ozw_manager.remove_driver(&device)
+        });
+        Ok(())
+    }
+
     pub fn get_state(&self) -> MutexGuard<State> {
         self.watcher.get_state()
     }
 
+    // Lets other in-crate subsystems (e.g. the device hot-plug watcher) emit a ZWaveNotification
+    // that didn't originate from the OpenZWave driver itself.
+    pub(crate) fn notify(&self, notification: ZWaveNotification) {
+        self.watcher.send(notification);
+    }
+
     pub fn write_configs(&self) {
         let state = self.get_state();
         let controllers = state.get_controllers();
@@ -239,6 +621,11 @@ impl ZWaveManager {
 
 #[derive(Clone, Debug)]
 pub enum ZWaveNotification {
+    DriverRemoved(String),
+    DriverReady(String),
+    ControllerDisconnected(String),
+    ControllerReconnected(String),
+
     ControllerReady(Controller),
     ControllerFailed(Controller),
     ControllerReset(Controller),
@@ -300,6 +687,11 @@ impl fmt::Display for ZWaveNotification {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let str;
         match *self {
+            ZWaveNotification::DriverRemoved(ref device)            => str = format!("DriverRemoved: {}", device),
+            ZWaveNotification::DriverReady(ref device)              => str = format!("DriverReady: {}", device),
+            ZWaveNotification::ControllerDisconnected(ref device)   => str = format!("ControllerDisconnected: {}", device),
+            ZWaveNotification::ControllerReconnected(ref device)    => str = format!("ControllerReconnected: {}", device),
+
             ZWaveNotification::ControllerReady(controller)          => str = format!("ControllerReady: {}", controller),
             ZWaveNotification::ControllerFailed(controller)         => str = format!("ControllerReady: {}", controller),
             ZWaveNotification::ControllerReset(controller)          => str = format!("ControllerReady: {}", controller),
@@ -362,18 +754,39 @@ impl fmt::Display for ZWaveNotification {
     }
 }
 
-// We'll get notifications coming from several threads that we don't control, so we'll have one
-// instance of mpsc::Sender for each thread because we don't control when to clone it. That's why
-// we use a Arc<Mutex<Sender>>. In the future we could implement Clone manually to clone the
-// Sender and wrap it in a new Mutex instead, but this would only be really useful if we have
-// several busy controllers. Another optimization if we have a lot of notifications coming is to
-// lazily clone the Sender the first time we receive a Notification on a thread -- but I don't see
-// how to see this without involving thread_local-bound structures. So keeping things simple for
-// now until we see there is a bottleneck here.
+// Abstracts the handful of Notification accessors ZWaveWatcher::handle_notification reads. The
+// real openzwave::notification::Notification is an FFI handle with no public constructor, so
+// without this trait the match-on-get_type() state machine below could never run against
+// synthetic input in a test; MockNotification (see the tests module) implements this instead.
+pub trait NotificationLike: fmt::Debug {
+    fn get_type(&self) -> NotificationType;
+    fn get_controller(&self) -> Controller;
+    fn get_node(&self) -> Node;
+    fn get_value_id(&self) -> ValueID;
+    fn get_event(&self) -> Option<u8>;
+    fn get_byte(&self) -> u8;
+    fn get_notification_code(&self) -> Option<NotificationCode>;
+}
+
+impl NotificationLike for Notification {
+    fn get_type(&self) -> NotificationType { Notification::get_type(self) }
+    fn get_controller(&self) -> Controller { Notification::get_controller(self) }
+    fn get_node(&self) -> Node { Notification::get_node(self) }
+    fn get_value_id(&self) -> ValueID { Notification::get_value_id(self) }
+    fn get_event(&self) -> Option<u8> { Notification::get_event(self) }
+    fn get_byte(&self) -> u8 { Notification::get_byte(self) }
+    fn get_notification_code(&self) -> Option<NotificationCode> { Notification::get_notification_code(self) }
+}
+
+// We'll get notifications coming from several threads that we don't control, so we keep the
+// registry of subscribers behind a Mutex<Vec<Sender>> rather than handing out a raw Sender per
+// thread. Every notification is fanned out to every live subscriber; a subscriber whose Receiver
+// has been dropped makes its Sender::send fail, and we prune it from the registry on the spot
+// instead of unwrapping into a panic.
 #[derive(Clone)]
 struct ZWaveWatcher {
     state: Arc<Mutex<State>>,
-    sender: Arc<Mutex<mpsc::Sender<ZWaveNotification>>>
+    senders: Arc<Mutex<Vec<mpsc::Sender<ZWaveNotification>>>>
 }
 
 impl ZWaveWatcher {
@@ -381,13 +794,41 @@ impl ZWaveWatcher {
         self.state.lock().unwrap()
     }
 
-    fn get_channel_sender(&self) -> MutexGuard<mpsc::Sender<ZWaveNotification>> {
-        self.sender.lock().unwrap()
+    fn subscribe(&self) -> mpsc::Receiver<ZWaveNotification> {
+        let (tx, rx) = mpsc::channel();
+        self.senders.lock().unwrap().push(tx);
+        rx
     }
-}
 
-impl manager::NotificationWatcher for ZWaveWatcher {
-    fn on_notification(&self, notification: &Notification) {
+    fn listener_count(&self) -> usize {
+        self.senders.lock().unwrap().len()
+    }
+
+    fn send(&self, notification: ZWaveNotification) {
+        let mut senders = self.senders.lock().unwrap();
+        senders.retain(|sender| sender.send(notification.clone()).is_ok());
+    }
+
+    // Builds a watcher with no live OpenZWave driver attached to it, so the match notification.get_type()
+    // state machine below can be exercised in tests without real hardware.
+    fn new_for_testing() -> (ZWaveWatcher, mpsc::Receiver<ZWaveNotification>) {
+        let (tx, rx) = mpsc::channel();
+        let watcher = ZWaveWatcher {
+            state: Arc::new(Mutex::new(State::new())),
+            senders: Arc::new(Mutex::new(vec![tx]))
+        };
+        (watcher, rx)
+    }
+
+    // Feeds a synthetic notification through the same match-on-get_type() logic a real driver
+    // thread would use, so a test can assert on the resulting State and on the ZWaveNotification
+    // it emits. Generic over NotificationLike (rather than taking a real Notification) because
+    // Notification is FFI-backed with no public constructor; tests drive this with MockNotification.
+    pub fn inject<N: NotificationLike>(&self, notification: &N) {
+        self.handle_notification(notification);
+    }
+
+    fn handle_notification<N: NotificationLike>(&self, notification: &N) {
         //println!("Received notification: {:?}", notification);
 
         match notification.get_type() {
@@ -398,32 +839,32 @@ impl manager::NotificationWatcher for ZWaveWatcher {
                     state.controllers.insert(controller, ControllerInfo::new());
                 }
 
-                self.get_channel_sender().send(ZWaveNotification::ControllerReady(controller)).unwrap();
+                self.send(ZWaveNotification::ControllerReady(controller));
             },
 
             NotificationType::Type_DriverFailed => {
                 let controller = notification.get_controller();
-                self.get_channel_sender().send(ZWaveNotification::ControllerFailed(controller)).unwrap();
+                self.send(ZWaveNotification::ControllerFailed(controller));
             },
 
             NotificationType::Type_DriverReset => {
                 let controller = notification.get_controller();
-                self.get_channel_sender().send(ZWaveNotification::ControllerReset(controller)).unwrap();
+                self.send(ZWaveNotification::ControllerReset(controller));
             },
 
             NotificationType::Type_AwakeNodesQueried => {
                 let controller = notification.get_controller();
-                self.get_channel_sender().send(ZWaveNotification::AwakeNodesQueried(controller)).unwrap();
+                self.send(ZWaveNotification::AwakeNodesQueried(controller));
             }
 
             NotificationType::Type_AllNodesQueriedSomeDead => {
                 let controller = notification.get_controller();
-                self.get_channel_sender().send(ZWaveNotification::AllNodesQueriedSomeDead(controller)).unwrap();
+                self.send(ZWaveNotification::AllNodesQueriedSomeDead(controller));
             }
 
             NotificationType::Type_AllNodesQueried => {
                 let controller = notification.get_controller();
-                self.get_channel_sender().send(ZWaveNotification::AllNodesQueried(controller)).unwrap();
+                self.send(ZWaveNotification::AllNodesQueried(controller));
             }
 
             NotificationType::Type_ControllerCommand => {
@@ -475,12 +916,12 @@ impl manager::NotificationWatcher for ZWaveWatcher {
                     let mut state = self.get_state();
                     state.controllers.insert(controller, controller_info);
                 }
-                self.get_channel_sender().send(zwn).unwrap();
+                self.send(zwn);
             },
 
             NotificationType::Type_NodeNew => {
                 let node = notification.get_node();
-                self.get_channel_sender().send(ZWaveNotification::NodeNew(node)).unwrap();
+                self.send(ZWaveNotification::NodeNew(node));
             },
 
             NotificationType::Type_NodeAdded => {
@@ -491,7 +932,7 @@ impl manager::NotificationWatcher for ZWaveWatcher {
                     state.add_node(node);
                 }
 
-                self.get_channel_sender().send(ZWaveNotification::NodeAdded(node)).unwrap();
+                self.send(ZWaveNotification::NodeAdded(node));
             },
 
             NotificationType::Type_NodeRemoved => {
@@ -502,36 +943,63 @@ impl manager::NotificationWatcher for ZWaveWatcher {
                     state.remove_node(node);
                 }
 
-                self.get_channel_sender().send(ZWaveNotification::NodeRemoved(node)).unwrap();
+                self.send(ZWaveNotification::NodeRemoved(node));
             },
 
             NotificationType::Type_NodeNaming => {
-                self.get_channel_sender().send(ZWaveNotification::NodeNaming(notification.get_node())).unwrap();
+                self.send(ZWaveNotification::NodeNaming(notification.get_node()));
             }
 
             NotificationType::Type_NodeProtocolInfo => {
-                self.get_channel_sender().send(ZWaveNotification::NodeProtocolInfo(notification.get_node())).unwrap();
+                self.send(ZWaveNotification::NodeProtocolInfo(notification.get_node()));
             }
 
             NotificationType::Type_NodeEvent => {
                 let node = notification.get_node();
-                self.get_channel_sender().send(ZWaveNotification::NodeEvent(node, notification.get_byte())).unwrap();
+                self.send(ZWaveNotification::NodeEvent(node, notification.get_byte()));
             },
 
             NotificationType::Type_Group => {
-                self.get_channel_sender().send(ZWaveNotification::Group(notification.get_node())).unwrap();
+                self.send(ZWaveNotification::Group(notification.get_node()));
             }
 
             NotificationType::Type_EssentialNodeQueriesComplete => {
-                self.get_channel_sender().send(ZWaveNotification::EssentialNodeQueriesComplete(notification.get_node())).unwrap();
+                let node = notification.get_node();
+
+                {
+                    let mut state = self.get_state();
+                    state.set_essential_node_queries_complete(node);
+                }
+
+                self.send(ZWaveNotification::EssentialNodeQueriesComplete(node));
             }
 
             NotificationType::Type_NodeQueriesComplete => {
-                self.get_channel_sender().send(ZWaveNotification::NodeQueriesComplete(notification.get_node())).unwrap();
+                let node = notification.get_node();
+
+                {
+                    let mut state = self.get_state();
+                    state.set_node_queries_complete(node);
+                }
+
+                self.send(ZWaveNotification::NodeQueriesComplete(node));
             }
 
             NotificationType::Type_Notification => {
                 let node = notification.get_node();
+                let status = match notification.get_notification_code() {
+                    Some(NotificationCode::Awake) => Some(NodeStatus::Awake),
+                    Some(NotificationCode::Sleep) => Some(NodeStatus::Asleep),
+                    Some(NotificationCode::Dead)  => Some(NodeStatus::Dead),
+                    Some(NotificationCode::Alive) => Some(NodeStatus::Alive),
+                    _ => None
+                };
+
+                if let Some(status) = status {
+                    let mut state = self.get_state();
+                    state.set_node_status(node, status);
+                }
+
                 let zwn = match notification.get_notification_code() {
                     Some(NotificationCode::MsgComplete) => ZWaveNotification::NotificationMsgComplete(node),
                     Some(NotificationCode::Timeout)     => ZWaveNotification::NotificationTimeout(node),
@@ -542,7 +1010,7 @@ impl manager::NotificationWatcher for ZWaveWatcher {
                     Some(NotificationCode::Alive)       => ZWaveNotification::NotificationAlive(node),
                     _                                   => ZWaveNotification::Generic(format!("Unknown NotificationCode {}", node))
                 };
-                self.get_channel_sender().send(zwn).unwrap();
+                self.send(zwn);
             }
 
             NotificationType::Type_ValueAdded => {
@@ -553,7 +1021,7 @@ impl manager::NotificationWatcher for ZWaveWatcher {
                     state.add_value_id(value_id);
                 }
 
-                self.get_channel_sender().send(ZWaveNotification::ValueAdded(value_id)).unwrap();
+                self.send(ZWaveNotification::ValueAdded(value_id));
             },
 
             NotificationType::Type_ValueChanged => {
@@ -564,7 +1032,7 @@ impl manager::NotificationWatcher for ZWaveWatcher {
                     state.add_value_id(value_id);
                 }
 
-                self.get_channel_sender().send(ZWaveNotification::ValueChanged(value_id)).unwrap();
+                self.send(ZWaveNotification::ValueChanged(value_id));
             },
 
             NotificationType::Type_ValueRemoved => {
@@ -575,32 +1043,133 @@ impl manager::NotificationWatcher for ZWaveWatcher {
                     state.remove_value_id(value_id);
                 }
 
-                self.get_channel_sender().send(ZWaveNotification::ValueRemoved(value_id)).unwrap();
+                self.send(ZWaveNotification::ValueRemoved(value_id));
             },
 
             NotificationType::Type_ValueRefreshed => {
                 let value_id = notification.get_value_id();
-                self.get_channel_sender().send(ZWaveNotification::ValueRefreshed(value_id)).unwrap();
+                self.send(ZWaveNotification::ValueRefreshed(value_id));
             },
 
             _ => {
                 let info = format!("Unknown notification: {:?}", notification);
-                self.get_channel_sender().send(ZWaveNotification::Generic(info)).unwrap();
+                self.send(ZWaveNotification::Generic(info));
             }
 
         }
     }
 }
 
+impl manager::NotificationWatcher for ZWaveWatcher {
+    fn on_notification(&self, notification: &Notification) {
+        self.handle_notification(notification);
+    }
+}
+
 pub enum ConfigPath<'a> {
     Default,
     Custom(&'a str)
 }
 
+// Using the same NetworkKey across every install (the old hardcoded default) lets anyone who
+// knows it decrypt traffic to any S0-secured device (locks, sensors) paired with this controller,
+// and a device stays paired across restarts only if the controller keeps using the same key.
+pub enum NetworkKey {
+    Fixed([u8; 16]),
+    ReuseOrGenerate,
+}
+
+fn format_network_key(key: &[u8; 16]) -> String {
+    key.iter().map(|byte| format!("0x{:02X}", byte)).collect::<Vec<_>>().join(", ")
+}
+
+fn generate_network_key() -> [u8; 16] {
+    let mut key = [0u8; 16];
+    rand::Rng::fill_bytes(&mut rand::thread_rng(), &mut key);
+    key
+}
+
+// The persisted file is a copy of the S0 security key, so it's written with owner-only
+// permissions rather than whatever the process umask happens to allow.
+fn persist_network_key(path: &str, key: &[u8; 16]) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut file = try!(fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|err| Error::NetworkKeyIoError(path.to_owned(), err)));
+    try!(file.write_all(key).map_err(|err| Error::NetworkKeyIoError(path.to_owned(), err)));
+    Ok(())
+}
+
+fn load_network_key(path: &str) -> Option<[u8; 16]> {
+    use std::io::Read;
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(_) => return None
+    };
+    let mut key = [0u8; 16];
+    match file.read_exact(&mut key) {
+        Ok(_) => Some(key),
+        Err(_) => None
+    }
+}
+
+fn resolve_network_key(network_key: &NetworkKey, user_path: &str) -> Result<[u8; 16]> {
+    let persisted_path = format!("{}/network_key.bin", user_path);
+
+    Ok(match *network_key {
+        NetworkKey::Fixed(key) => key,
+        NetworkKey::ReuseOrGenerate => match load_network_key(&persisted_path) {
+            Some(key) => key,
+            None => {
+                let key = generate_network_key();
+                try!(persist_network_key(&persisted_path, &key));
+                key
+            }
+        }
+    })
+}
+
 pub struct InitOptions<'a, 'b> {
     pub devices: Option<Vec<String>>,
     pub config_path: ConfigPath<'a>,
-    pub user_path: &'b str
+    pub user_path: &'b str,
+    pub network_key: NetworkKey,
+}
+
+// Retries opening a slow-to-enumerate USB device a bounded number of times with exponential
+// backoff, so a transient "device busy/interrupted" read at startup doesn't get reported as
+// "the stick is gone" the way a single failed open would.
+const DEVICE_OPEN_MAX_ATTEMPTS: u32 = 5;
+const DEVICE_OPEN_INITIAL_BACKOFF_MS: u64 = 50;
+const DEVICE_OPEN_MAX_BACKOFF_MS: u64 = 800;
+
+fn open_device_with_retry(device: &str) -> Result<fs::File> {
+    use std::time::Duration;
+    use std::thread;
+
+    let mut backoff_ms = DEVICE_OPEN_INITIAL_BACKOFF_MS;
+
+    for attempt in 1..(DEVICE_OPEN_MAX_ATTEMPTS + 1) {
+        match fs::File::open(device) {
+            Ok(file) => return Ok(file),
+            Err(io_err) => {
+                let error = Error::CannotReadDevice(device.to_owned(), io_err);
+                if attempt == DEVICE_OPEN_MAX_ATTEMPTS || !error.is_transient() {
+                    return Err(error);
+                }
+                thread::sleep(Duration::from_millis(backoff_ms));
+                backoff_ms = ::std::cmp::min(backoff_ms * 2, DEVICE_OPEN_MAX_BACKOFF_MS);
+            }
+        }
+    }
+
+    unreachable!()
 }
 
 pub fn init(options: &InitOptions) -> Result<(ZWaveManager, mpsc::Receiver<ZWaveNotification>)> {
@@ -611,31 +1180,150 @@ pub fn init(options: &InitOptions) -> Result<(ZWaveManager, mpsc::Receiver<ZWave
 
     let mut ozw_options = try!(options::Options::create(config_path, options.user_path, "--SaveConfiguration true --DumpTriggerLevel 0 --ConsoleOutput false"));
 
-    // TODO: The NetworkKey should really be derived from something unique
-    //       about the foxbox that we're running on. This particular set of
-    //       values happens to be the default that domoticz uses.
-    try!(options::Options::add_option_string(&mut ozw_options, "NetworkKey", "0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F, 0x10", false));
+    let network_key = try!(resolve_network_key(&options.network_key, options.user_path));
+    try!(options::Options::add_option_string(&mut ozw_options, "NetworkKey", &format_network_key(&network_key), false));
 
     let manager = try!(manager::Manager::create(ozw_options));
     let (mut zwave_manager, rx) = ZWaveManager::new(manager);
     try!(zwave_manager.add_watcher());
 
-    let devices = options.devices.clone().unwrap_or(get_default_devices());
+    let devices = options.devices.clone().unwrap_or_else(get_default_devices);
     for device in devices.iter() {
-        try !(
-            fs::File::open(&device).map_err(|err| Error::CannotReadDevice(device.clone(), err))
-        );
+        try!(open_device_with_retry(device));
         //println!("found device {}", device);
 
         try!(zwave_manager.add_driver(&device));
     }
 
+    // Scenes live on the controller, not in this process, so a fresh process needs to pull
+    // whatever's already there instead of waiting to see its own mutating scene calls.
+    try!(zwave_manager.refresh_scenes());
+
     Ok((zwave_manager, rx))
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // A synthetic stand-in for openzwave::notification::Notification, which has no public
+    // constructor. Only the fields a given test cares about need to be set: get_event,
+    // get_byte and get_notification_code fall back to None/0, while get_type, get_controller,
+    // get_node and get_value_id panic if their field was left unset.
+    #[derive(Debug, Default)]
+    struct MockNotification {
+        notification_type: Option<NotificationType>,
+        controller: Option<Controller>,
+        node: Option<Node>,
+        value_id: Option<ValueID>,
+        event: Option<u8>,
+        byte: u8,
+        notification_code: Option<NotificationCode>,
+    }
+
+    impl NotificationLike for MockNotification {
+        fn get_type(&self) -> NotificationType {
+            self.notification_type.expect("test notification type not set")
+        }
+
+        fn get_controller(&self) -> Controller {
+            self.controller.expect("test controller not set")
+        }
+
+        fn get_node(&self) -> Node {
+            self.node.expect("test node not set")
+        }
+
+        fn get_value_id(&self) -> ValueID {
+            self.value_id.expect("test value id not set")
+        }
+
+        fn get_event(&self) -> Option<u8> {
+            self.event
+        }
+
+        fn get_byte(&self) -> u8 {
+            self.byte
+        }
+
+        fn get_notification_code(&self) -> Option<NotificationCode> {
+            self.notification_code
+        }
+    }
+
+    fn test_controller() -> Controller {
+        Controller::new(1)
+    }
+
+    fn test_node() -> Node {
+        Node::new(1, 2)
+    }
+
+    fn test_value_id() -> ValueID {
+        ValueID::new(1, 2, ValueGenre::ValueGenre_User, CommandClass::COMMAND_CLASS_SWITCH_BINARY, 0, 0, ValueType::ValueType_Bool)
+    }
+
+    fn recv(rx: &mpsc::Receiver<ZWaveNotification>) -> ZWaveNotification {
+        rx.recv_timeout(Duration::from_secs(1)).expect("expected a notification")
+    }
+
     #[test]
-    fn it_works() {
+    fn node_added_updates_state_and_notifies() {
+        let (watcher, rx) = ZWaveWatcher::new_for_testing();
+        let node = test_node();
+
+        watcher.inject(&MockNotification {
+            notification_type: Some(NotificationType::Type_NodeAdded),
+            node: Some(node),
+            ..MockNotification::default()
+        });
+
+        assert!(watcher.get_state().get_nodes().contains(&node));
+        match recv(&rx) {
+            ZWaveNotification::NodeAdded(notified) => assert_eq!(notified, node),
+            other => panic!("expected ZWaveNotification::NodeAdded, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn controller_command_in_progress_updates_state_and_notifies() {
+        let (watcher, rx) = ZWaveWatcher::new_for_testing();
+        let controller = test_controller();
+
+        watcher.inject(&MockNotification {
+            notification_type: Some(NotificationType::Type_ControllerCommand),
+            controller: Some(controller),
+            event: Some(ControllerState::InProgress as u8),
+            ..MockNotification::default()
+        });
+
+        let info = watcher.get_state().get_controller_info(&controller).cloned().expect("controller should be tracked");
+        match info.last_state {
+            ControllerState::InProgress => {},
+            other => panic!("expected ControllerState::InProgress, got {:?}", other),
+        }
+        match recv(&rx) {
+            ZWaveNotification::StateInProgress(notified) => assert_eq!(notified, controller),
+            other => panic!("expected ZWaveNotification::StateInProgress, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn value_changed_updates_state_and_notifies() {
+        let (watcher, rx) = ZWaveWatcher::new_for_testing();
+        let value_id = test_value_id();
+
+        watcher.inject(&MockNotification {
+            notification_type: Some(NotificationType::Type_ValueChanged),
+            value_id: Some(value_id),
+            ..MockNotification::default()
+        });
+
+        assert!(watcher.get_state().get_values().contains(&value_id));
+        match recv(&rx) {
+            ZWaveNotification::ValueChanged(notified) => assert_eq!(notified, value_id),
+            other => panic!("expected ZWaveNotification::ValueChanged, got {:?}", other),
+        }
     }
 }
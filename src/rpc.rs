@@ -0,0 +1,162 @@
+// Optional headless-hub mode: expose a ZWaveManager over a plain line-delimited JSON protocol so a
+// remote process can drive the network without linking against the OpenZWave C++ library itself.
+// Gated behind the `rpc` feature since most consumers embed this crate directly in one binary.
+
+use Error;
+use Result;
+use Value;
+use ValueID;
+use ValueType;
+use ZWaveManager;
+use serde_json;
+use serde_json::Value as Json;
+use std::io::{ BufRead, BufReader, Write };
+use std::net::{ TcpListener, TcpStream };
+use std::sync::Arc;
+use std::thread;
+
+// Starts accepting connections on `addr` and blocks forever, handing each connection its own
+// request/response loop plus a dedicated notification-forwarding thread. The manager is shared
+// across every connected client, and its single notification stream is multiplexed out to all of
+// them via ZWaveManager::subscribe.
+pub fn serve(manager: Arc<ZWaveManager>, addr: &str) -> Result<()> {
+    let listener = try!(TcpListener::bind(addr).map_err(Error::RpcIoError));
+
+    for stream in listener.incoming() {
+        // A single failed accept() (EMFILE, a client that reset mid-handshake, ...) shouldn't take
+        // down the listener and disconnect every other client, so log it and keep serving.
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                error!("[OpenzwaveStateful] rpc accept error: {}", err);
+                continue;
+            }
+        };
+        let manager = manager.clone();
+        thread::spawn(move || {
+            if let Err(err) = handle_client(manager, stream) {
+                error!("[OpenzwaveStateful] rpc client error: {}", err);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn handle_client(manager: Arc<ZWaveManager>, stream: TcpStream) -> Result<()> {
+    let notifications = manager.subscribe();
+
+    {
+        let mut writer = try!(stream.try_clone().map_err(Error::RpcIoError));
+        thread::spawn(move || {
+            while let Ok(notification) = notifications.recv() {
+                let line = json!({ "notification": notification.to_string() }).to_string();
+                if writer.write_all(line.as_bytes()).is_err() || writer.write_all(b"\n").is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    let reader = BufReader::new(try!(stream.try_clone().map_err(Error::RpcIoError)));
+    let mut writer = stream;
+
+    for line in reader.lines() {
+        let line = try!(line.map_err(Error::RpcIoError));
+        let response = dispatch(&manager, &line).unwrap_or_else(|err| json!({ "error": err.to_string() }));
+        try!(writer.write_all(response.to_string().as_bytes()).map_err(Error::RpcIoError));
+        try!(writer.write_all(b"\n").map_err(Error::RpcIoError));
+    }
+
+    Ok(())
+}
+
+fn dispatch(manager: &ZWaveManager, line: &str) -> Result<Json> {
+    let request: Json = try!(serde_json::from_str(line).map_err(Error::RpcJsonError));
+    let method = request.get("method").and_then(|m| m.as_str()).unwrap_or("");
+
+    match method {
+        "get_state" => Ok(serde_json::from_str(&manager.to_json()).unwrap_or(json!({}))),
+
+        "add_node" => {
+            let home_id = get_u64_arg(&request, "home_id");
+            let secure = request.get("secure").and_then(|v| v.as_bool()).unwrap_or(false);
+            try!(manager.add_node(home_id as u32, secure));
+            Ok(json!({ "ok": true }))
+        },
+
+        "remove_node" => {
+            let home_id = get_u64_arg(&request, "home_id");
+            try!(manager.remove_node(home_id as u32));
+            Ok(json!({ "ok": true }))
+        },
+
+        "heal_network" => {
+            let home_id = get_u64_arg(&request, "home_id");
+            let do_rr = request.get("do_rr").and_then(|v| v.as_bool()).unwrap_or(false);
+            manager.heal_network(home_id as u32, do_rr);
+            Ok(json!({ "ok": true }))
+        },
+
+        "test_network" => {
+            let home_id = get_u64_arg(&request, "home_id");
+            let count = get_u64_arg(&request, "count");
+            manager.test_network(home_id as u32, count as u32);
+            Ok(json!({ "ok": true }))
+        },
+
+        "get_value" | "set_value" | "refresh_value" => {
+            let id = get_u64_arg(&request, "id");
+            let value_id = try!(manager.get_state().find_value_by_id(id).ok_or(Error::RpcUnknownValue(id)));
+
+            match method {
+                "get_value" => {
+                    let value = try!(manager.get_value(&value_id));
+                    Ok(json!({ "value": format!("{:?}", value) }))
+                },
+                "refresh_value" => {
+                    try!(manager.refresh_value(&value_id));
+                    Ok(json!({ "ok": true }))
+                },
+                _ => {
+                    let value = value_from_json(&value_id, request.get("value"));
+                    try!(manager.set_value(&value_id, value));
+                    Ok(json!({ "ok": true }))
+                }
+            }
+        },
+
+        other => Ok(json!({ "error": format!("unknown method: {}", other) })),
+    }
+}
+
+fn get_u64_arg(request: &Json, key: &str) -> u64 {
+    request.get(key).and_then(|v| v.as_u64()).unwrap_or(0)
+}
+
+// Mirrors ZWaveManager::get_value's match on the ValueID's own type, so a remote set_value call
+// reaches the type-correct setter (set_value_bool, set_value_byte, ...) instead of always going
+// through set_value_string, which silently no-ops or errors for every non-string value type.
+fn value_from_json(value_id: &ValueID, json: Option<&Json>) -> Value {
+    match value_id.get_type() {
+        ValueType::ValueType_Bool => Value::Bool(json.and_then(|v| v.as_bool()).unwrap_or(false)),
+        ValueType::ValueType_Byte => Value::Byte(json.and_then(|v| v.as_u64()).unwrap_or(0) as u8),
+        ValueType::ValueType_Short => Value::Short(json.and_then(|v| v.as_i64()).unwrap_or(0) as i16),
+        ValueType::ValueType_Int => Value::Int(json.and_then(|v| v.as_i64()).unwrap_or(0) as i32),
+        ValueType::ValueType_Decimal => Value::Decimal(json.map(json_to_raw_string).unwrap_or_default()),
+        ValueType::ValueType_List => Value::List(json.map(json_to_raw_string).unwrap_or_default()),
+        ValueType::ValueType_String => Value::String(json.map(json_to_raw_string).unwrap_or_default()),
+        ValueType::ValueType_Raw => Value::Raw(json.and_then(|v| v.as_array())
+            .map(|bytes| bytes.iter().filter_map(|b| b.as_u64()).map(|b| b as u8).collect())
+            .unwrap_or_default()),
+        ValueType::ValueType_Button => Value::Button(json.and_then(|v| v.as_bool()).unwrap_or(false)),
+        ValueType::ValueType_Schedule => Value::Schedule,
+    }
+}
+
+// request.get("value") is a serde_json::Value, which wraps JSON strings in quotes under
+// Display/to_string; callers that want the bare string (Decimal/List/String) need the unquoted
+// form when the client sent a JSON string, falling back to to_string() for other JSON types.
+fn json_to_raw_string(json: &Json) -> String {
+    json.as_str().map(str::to_owned).unwrap_or_else(|| json.to_string())
+}